@@ -0,0 +1,72 @@
+use std::{env, path::PathBuf};
+
+use crate::sign::Algorithm;
+
+/// Server-side configuration loaded from the environment at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Secret key used to sign and verify request URLs. Request signing is
+    /// disabled (only the `unsafe` bypass is accepted) when this is unset,
+    /// mirroring thumbor's `SECURITY_KEY` behaviour.
+    pub signing_key: Option<String>,
+    /// Algorithm used to compute the HMAC signature.
+    pub signing_algorithm: Algorithm,
+    /// `max-age` (in seconds) advertised via `Cache-Control` on processed
+    /// responses.
+    pub cache_max_age: u64,
+    /// Region of the S3-compatible bucket backing `s3://` source locators.
+    /// S3 fetching is disabled unless this and `s3_bucket` are both set.
+    pub s3_region: Option<String>,
+    /// Name of the only bucket `s3://` locators are allowed to address.
+    /// Requests naming any other bucket are rejected, so a signed request
+    /// can't be used to read from buckets the deployment didn't intend to
+    /// expose. S3 fetching is disabled unless this and `s3_region` are both
+    /// set.
+    pub s3_bucket: Option<String>,
+    /// Custom endpoint for the S3-compatible bucket, e.g. for MinIO or
+    /// another non-AWS provider. Falls back to the AWS default when unset.
+    pub s3_endpoint: Option<String>,
+    /// Root directory `file://` source locators are resolved against.
+    /// Locators are rejected if they'd escape this directory (absolute
+    /// paths or `..` segments). File fetching is disabled when this is
+    /// unset.
+    pub file_root: Option<PathBuf>,
+    /// Connect timeout for upstream HTTP(S) source fetches.
+    pub upstream_connect_timeout_secs: u64,
+    /// Overall request timeout for upstream HTTP(S) source fetches.
+    pub upstream_timeout_secs: u64,
+    /// `User-Agent` sent on upstream HTTP(S) source fetches.
+    pub upstream_user_agent: String,
+    /// Optional HTTP/SOCKS proxy to route upstream HTTP(S) source fetches
+    /// through, e.g. when the source host is geo-blocked or rate-limited.
+    /// Does not apply to `file://` or `s3://` locators.
+    pub upstream_proxy: Option<String>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Self {
+            signing_key: env::var("THUMBOR_SECURITY_KEY").ok().filter(|k| !k.is_empty()),
+            signing_algorithm: Algorithm::from_env(env::var("THUMBOR_SIGNING_ALGORITHM").ok().as_deref()),
+            cache_max_age: env::var("THUMBOR_CACHE_MAX_AGE")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(86400),
+            s3_region: env::var("THUMBOR_S3_REGION").ok(),
+            s3_bucket: env::var("THUMBOR_S3_BUCKET").ok(),
+            s3_endpoint: env::var("THUMBOR_S3_ENDPOINT").ok(),
+            file_root: env::var("THUMBOR_FILE_ROOT").ok().map(PathBuf::from),
+            upstream_connect_timeout_secs: env::var("THUMBOR_UPSTREAM_CONNECT_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+            upstream_timeout_secs: env::var("THUMBOR_UPSTREAM_TIMEOUT_SECS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(10),
+            upstream_user_agent: env::var("THUMBOR_UPSTREAM_USER_AGENT")
+                .unwrap_or_else(|_| concat!("thumbor-rs/", env!("CARGO_PKG_VERSION")).to_string()),
+            upstream_proxy: env::var("THUMBOR_UPSTREAM_PROXY").ok(),
+        }
+    }
+}
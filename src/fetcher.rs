@@ -0,0 +1,344 @@
+use std::{
+    fmt,
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use reqwest::StatusCode;
+
+use crate::config::Config;
+
+/// Error fetching a source image. Upstream HTTP errors keep their original
+/// status code so callers can surface something more useful than a blanket
+/// `400 Bad Request`.
+#[derive(Debug, Clone)]
+pub enum FetchError {
+    Upstream(StatusCode),
+    Other(String),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Upstream(status) => write!(f, "upstream responded with {status}"),
+            FetchError::Other(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> Self {
+        match err.status() {
+            Some(status) => FetchError::Upstream(status),
+            None => FetchError::Other(err.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for FetchError {
+    fn from(err: std::io::Error) -> Self {
+        FetchError::Other(err.to_string())
+    }
+}
+
+pub type FetchResult = Result<Bytes, FetchError>;
+
+/// Fetches the bytes of a source image identified by `locator`.
+/// Implementations are selected by the locator's URI scheme (`http(s)://`,
+/// `file://`, `s3://bucket/key`).
+#[async_trait]
+pub trait Fetcher: Send + Sync {
+    async fn fetch(&self, locator: &str) -> FetchResult;
+}
+
+/// Builds the single `reqwest::Client` shared by every HTTP(S) fetch, so
+/// upstream connections get pooled and reused instead of a fresh client
+/// (and fresh TCP/TLS handshake) per request.
+pub fn build_http_client(config: &Config) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(config.upstream_connect_timeout_secs))
+        .timeout(Duration::from_secs(config.upstream_timeout_secs))
+        .user_agent(&config.upstream_user_agent)
+        .redirect(reqwest::redirect::Policy::limited(5));
+
+    if let Some(proxy) = &config.upstream_proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+    }
+
+    Ok(builder.build()?)
+}
+
+/// Fetches source images over plain HTTP(S) using the shared, pooled
+/// client.
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+impl HttpFetcher {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl Fetcher for HttpFetcher {
+    async fn fetch(&self, locator: &str) -> FetchResult {
+        let resp = self.client.get(locator).send().await?.error_for_status()?;
+        Ok(resp.bytes().await?)
+    }
+}
+
+/// Fetches source images from the local filesystem, for deployments that
+/// front a private image directory instead of the public internet. Locators
+/// are resolved against a configured root directory; a locator that would
+/// escape it (an absolute path or a `..` segment) is rejected rather than
+/// read, since the `url` path segment is client-supplied and, outside of
+/// signature verification, unsanitized.
+pub struct FileFetcher {
+    root: PathBuf,
+}
+
+impl FileFetcher {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Resolves `locator`'s path component to somewhere inside `root`,
+    /// rejecting an absolute path or any component that isn't a plain path
+    /// segment.
+    fn resolve(&self, locator: &str) -> Result<PathBuf, FetchError> {
+        let rest = locator.strip_prefix("file://").unwrap_or(locator);
+        if rest.starts_with('/') {
+            return Err(FetchError::Other(format!("invalid file locator: {locator}")));
+        }
+        let mut resolved = self.root.clone();
+        for component in Path::new(rest).components() {
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                _ => return Err(FetchError::Other(format!("invalid file locator: {locator}"))),
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+#[async_trait]
+impl Fetcher for FileFetcher {
+    async fn fetch(&self, locator: &str) -> FetchResult {
+        let path = self.resolve(locator)?;
+        let data = tokio::fs::read(path).await?;
+        Ok(Bytes::from(data))
+    }
+}
+
+/// Fetches source images from an S3-compatible object store. Requests are
+/// restricted to a single configured bucket: the bucket name in an
+/// `s3://bucket/key` locator is client-supplied, so without this the
+/// service's AWS credentials could be used to read any bucket they can
+/// reach, not just the one the deployment intended to expose.
+pub struct S3Fetcher {
+    client: aws_sdk_s3::Client,
+    bucket: String,
+}
+
+impl S3Fetcher {
+    pub async fn from_config(config: &Config) -> Option<Self> {
+        let region = config.s3_region.clone()?;
+        let bucket = config.s3_bucket.clone()?;
+        let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region(aws_sdk_s3::config::Region::new(region));
+        if let Some(endpoint) = &config.s3_endpoint {
+            loader = loader.endpoint_url(endpoint);
+        }
+        let sdk_config = loader.load().await;
+        Some(Self {
+            client: aws_sdk_s3::Client::new(&sdk_config),
+            bucket,
+        })
+    }
+}
+
+/// Parses an `s3://bucket/key` locator and checks its bucket against
+/// `allowed`, the only bucket this deployment is configured to serve.
+fn parse_s3_locator<'a>(locator: &'a str, allowed: &str) -> Result<(&'a str, &'a str), FetchError> {
+    let rest = locator
+        .strip_prefix("s3://")
+        .ok_or_else(|| FetchError::Other(format!("not an s3 locator: {locator}")))?;
+    let (bucket, key) = rest
+        .split_once('/')
+        .ok_or_else(|| FetchError::Other(format!("s3 locator missing object key: {locator}")))?;
+    if bucket != allowed {
+        return Err(FetchError::Other(format!(
+            "s3 bucket {bucket} is not the configured bucket {allowed}"
+        )));
+    }
+    Ok((bucket, key))
+}
+
+#[async_trait]
+impl Fetcher for S3Fetcher {
+    async fn fetch(&self, locator: &str) -> FetchResult {
+        let (bucket, key) = parse_s3_locator(locator, &self.bucket)?;
+        let output = self
+            .client
+            .get_object()
+            .bucket(bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| FetchError::Other(e.to_string()))?;
+        let data = output
+            .body
+            .collect()
+            .await
+            .map_err(|e| FetchError::Other(e.to_string()))?;
+        Ok(data.into_bytes())
+    }
+}
+
+/// Dispatches to the `Fetcher` implementation matching `locator`'s URI
+/// scheme, so `generate` doesn't need to know which backend served a given
+/// source image.
+pub struct Dispatcher {
+    http: HttpFetcher,
+    file: Option<FileFetcher>,
+    s3: Option<S3Fetcher>,
+}
+
+impl Dispatcher {
+    pub async fn from_config(config: &Config, http_client: reqwest::Client) -> Self {
+        Self {
+            http: HttpFetcher::new(http_client),
+            file: config.file_root.clone().map(FileFetcher::new),
+            s3: S3Fetcher::from_config(config).await,
+        }
+    }
+}
+
+#[async_trait]
+impl Fetcher for Dispatcher {
+    async fn fetch(&self, locator: &str) -> FetchResult {
+        if locator.starts_with("file://") {
+            self.file
+                .as_ref()
+                .ok_or_else(|| FetchError::Other("file fetching is not configured".to_string()))?
+                .fetch(locator)
+                .await
+        } else if locator.starts_with("s3://") {
+            self.s3
+                .as_ref()
+                .ok_or_else(|| FetchError::Other("S3 fetching is not configured".to_string()))?
+                .fetch(locator)
+                .await
+        } else {
+            self.http.fetch(locator).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("thumbor-fetcher-test-{name}-{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn file_fetcher_reads_a_file_inside_root() {
+        let root = temp_dir("ok").await;
+        tokio::fs::write(root.join("a.jpg"), b"hello").await.unwrap();
+        let fetcher = FileFetcher::new(root.clone());
+
+        let data = fetcher.fetch("file://a.jpg").await.unwrap();
+
+        assert_eq!(&data[..], b"hello");
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+
+    #[tokio::test]
+    async fn file_fetcher_rejects_parent_dir_escape() {
+        let root = temp_dir("escape").await;
+        let fetcher = FileFetcher::new(root.clone());
+
+        let err = fetcher.fetch("file://../../etc/passwd").await.unwrap_err();
+
+        assert!(matches!(err, FetchError::Other(_)));
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+
+    #[tokio::test]
+    async fn file_fetcher_rejects_absolute_path_escape() {
+        let root = temp_dir("abs").await;
+        let fetcher = FileFetcher::new(root.clone());
+
+        // `/etc/passwd` is guaranteed to exist on the test host, so unlike a
+        // nonexistent path this can't pass by accident: if containment were
+        // broken, the fetch would succeed and return its contents instead.
+        let err = fetcher.fetch("file:///etc/passwd").await.unwrap_err();
+
+        assert!(matches!(err, FetchError::Other(_)));
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+
+    #[test]
+    fn s3_locator_accepts_the_configured_bucket() {
+        let (bucket, key) = parse_s3_locator("s3://images/photos/a.jpg", "images").unwrap();
+        assert_eq!(bucket, "images");
+        assert_eq!(key, "photos/a.jpg");
+    }
+
+    #[test]
+    fn s3_locator_rejects_other_buckets() {
+        let err = parse_s3_locator("s3://other-bucket/a.jpg", "images").unwrap_err();
+        assert!(matches!(err, FetchError::Other(_)));
+    }
+
+    #[tokio::test]
+    async fn dispatcher_reports_unconfigured_file_backend() {
+        let dispatcher = Dispatcher {
+            http: HttpFetcher::new(reqwest::Client::new()),
+            file: None,
+            s3: None,
+        };
+
+        let err = dispatcher.fetch("file://a.jpg").await.unwrap_err();
+
+        assert!(matches!(err, FetchError::Other(_)));
+    }
+
+    #[tokio::test]
+    async fn dispatcher_reports_unconfigured_s3_backend() {
+        let dispatcher = Dispatcher {
+            http: HttpFetcher::new(reqwest::Client::new()),
+            file: None,
+            s3: None,
+        };
+
+        let err = dispatcher.fetch("s3://bucket/key.jpg").await.unwrap_err();
+
+        assert!(matches!(err, FetchError::Other(_)));
+    }
+
+    #[tokio::test]
+    async fn dispatcher_dispatches_file_locators_to_the_file_fetcher() {
+        let root = temp_dir("dispatch").await;
+        tokio::fs::write(root.join("a.jpg"), b"hello").await.unwrap();
+        let dispatcher = Dispatcher {
+            http: HttpFetcher::new(reqwest::Client::new()),
+            file: Some(FileFetcher::new(root.clone())),
+            s3: None,
+        };
+
+        let data = dispatcher.fetch("file://a.jpg").await.unwrap();
+
+        assert_eq!(&data[..], b"hello");
+        tokio::fs::remove_dir_all(&root).await.ok();
+    }
+}
@@ -0,0 +1,71 @@
+use axum::http::{HeaderMap, HeaderValue};
+use httpdate::{fmt_http_date, parse_http_date};
+use std::time::SystemTime;
+
+/// Formats a strong ETag from the caller's response cache key (which must
+/// fold in the negotiated output representation, not just `spec`/`url`) so
+/// that identical transforms of the same source image always validate to
+/// the same entity tag, and distinct representations never collide.
+pub fn etag(key: u64) -> String {
+    format!("\"{key:x}\"")
+}
+
+/// Whether the inbound `If-None-Match` header matches `etag`. Per RFC 7232
+/// this is a comma-separated list and also accepts the `*` wildcard.
+pub fn if_none_match_satisfied(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(value) = headers.get(axum::http::header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    value.split(',').map(str::trim).any(|candidate| candidate == "*" || candidate == etag)
+}
+
+/// Whether the inbound `If-Modified-Since` header is at or after
+/// `last_modified`, meaning the client's cached copy is still fresh.
+pub fn if_modified_since_satisfied(headers: &HeaderMap, last_modified: SystemTime) -> bool {
+    headers
+        .get(axum::http::header::IF_MODIFIED_SINCE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| parse_http_date(v).ok())
+        .is_some_and(|since| since >= last_modified)
+}
+
+/// Applies the standard cache-validation headers (`Cache-Control`,
+/// `Last-Modified`, `ETag`) to a response, shared between the full and the
+/// `304 Not Modified` responses so the two never drift apart.
+pub fn apply_validators(headers: &mut HeaderMap, max_age: u64, last_modified: SystemTime, etag: &str) {
+    headers.insert(
+        axum::http::header::CACHE_CONTROL,
+        HeaderValue::from_str(&format!("public, max-age={max_age}")).unwrap(),
+    );
+    headers.insert(
+        axum::http::header::LAST_MODIFIED,
+        HeaderValue::from_str(&fmt_http_date(last_modified)).unwrap(),
+    );
+    headers.insert(axum::http::header::ETAG, HeaderValue::from_str(etag).unwrap());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_etag() {
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::IF_NONE_MATCH, HeaderValue::from_static("\"abc\""));
+        assert!(if_none_match_satisfied(&headers, "\"abc\""));
+    }
+
+    #[test]
+    fn matches_wildcard_etag() {
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::IF_NONE_MATCH, HeaderValue::from_static("*"));
+        assert!(if_none_match_satisfied(&headers, "\"abc\""));
+    }
+
+    #[test]
+    fn rejects_mismatched_etag() {
+        let mut headers = HeaderMap::new();
+        headers.insert(axum::http::header::IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!if_none_match_satisfied(&headers, "\"abc\""));
+    }
+}
@@ -14,7 +14,7 @@ use axum::{
 use bytes::Bytes;
 use lru::LruCache;
 use pb::ImageSpec;
-use percent_encoding::{percent_decode_str, percent_encode, NON_ALPHANUMERIC};
+use percent_encoding::{percent_encode, NON_ALPHANUMERIC};
 use tokio::sync::Mutex;
 use tower::ServiceBuilder;
 use tower_http::add_extension::AddExtensionLayer;
@@ -27,18 +27,78 @@ mod engine;
 use engine::{Engine, Photon};
 use image::ImageFormat;
 
+mod config;
+use config::Config;
+
+mod sign;
+
+mod negotiate;
+use negotiate::{content_type, negotiate_format};
+
+mod httpcache;
+
+mod fetcher;
+use fetcher::{build_http_client, Dispatcher, FetchResult, Fetcher};
+
+mod singleflight;
+use singleflight::{Lead, SingleFlight};
+
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
 type Cache = Arc<Mutex<LruCache<u64, Bytes>>>;
 
+/// Time the server started, used as a conservative `Last-Modified` for
+/// every processed image since a given `spec`/`url` pair always renders to
+/// the same bytes for the lifetime of the process.
+fn server_start() -> SystemTime {
+    static START: OnceLock<SystemTime> = OnceLock::new();
+    *START.get_or_init(SystemTime::now)
+}
+
+/// Combines `spec` and `url` into the cache key used to look up the
+/// (unprocessed) source image, independent of the output representation.
+fn cache_key(spec: &str, url: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    spec.hash(&mut hasher);
+    url.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Folds the negotiated output `format` and `quality` into `source_key` to
+/// derive the response `ETag`. A strong validator must identify exactly one
+/// representation (RFC 7232): two formats rendered from the same `spec`/`url`
+/// are different representations and must not share an `ETag`, or a client
+/// revalidating with a different `Accept` could get back a `304` pointing at
+/// a cached body of the wrong format. This relies on `Engine::generate`
+/// actually varying its output with `quality` for every format it claims to
+/// support (see `engine::Photon::generate`) — otherwise two requests that
+/// render identical bytes would still be keyed, and cached, separately.
+fn response_key(source_key: u64, format: ImageFormat, quality: Option<u8>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source_key.hash(&mut hasher);
+    content_type(format).hash(&mut hasher);
+    quality.hash(&mut hasher);
+    hasher.finish()
+}
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt::init();
     let cache: Cache = Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(1024).unwrap())));
+    let config = Arc::new(Config::from_env());
+    let http_client = build_http_client(&config).expect("failed to build upstream HTTP client");
+    let fetcher: Arc<dyn Fetcher> = Arc::new(Dispatcher::from_config(&config, http_client).await);
+    let in_flight = SingleFlight::default();
 
     let router = Router::new()
-        .route("/image/:spec/:url", get(generate))
+        .route("/image/:signature/:spec/:url", get(generate))
         .layer(
             ServiceBuilder::new()
                 .layer(AddExtensionLayer::new(cache))
+                .layer(AddExtensionLayer::new(config))
+                .layer(AddExtensionLayer::new(fetcher))
+                .layer(AddExtensionLayer::new(in_flight))
                 .into_inner(),
         );
 
@@ -50,18 +110,52 @@ async fn main() {
 }
 
 async fn generate(
-    Path((spec, url)): Path<(String, String)>,
+    Path((signature, spec, url)): Path<(String, String, String)>,
+    request_headers: HeaderMap,
     Extension(cache): Extension<Cache>,
-) -> Result<(HeaderMap, Vec<u8>), StatusCode> {
-    let url = percent_decode_str(&url).decode_utf8_lossy();
+    Extension(config): Extension<Arc<Config>>,
+    Extension(fetcher): Extension<Arc<dyn Fetcher>>,
+    Extension(in_flight): Extension<SingleFlight>,
+) -> Result<(StatusCode, HeaderMap, Vec<u8>), StatusCode> {
+    // axum's `Path` extractor percent-decodes matched segments before the
+    // handler ever sees them, but a signing client (see `print_test_url`)
+    // computes its HMAC over the percent-encoded bytes it put on the wire.
+    // Re-encode here so verification checks the same representation the
+    // client actually signed, rather than the already-decoded string.
+    let encoded_url = percent_encode(url.as_bytes(), NON_ALPHANUMERIC).to_string();
+    verify_signature(&config, &signature, &spec, &encoded_url)?;
+
+    let source_key = cache_key(&spec, &url);
+
     let spec: ImageSpec = spec
         .as_str()
         .try_into()
         .map_err(|_| StatusCode::BAD_REQUEST)?;
 
-    let data = retrieve_image(&url, cache)
+    let accept = request_headers
+        .get(axum::http::header::ACCEPT)
+        .and_then(|v| v.to_str().ok());
+    let format = spec.format.unwrap_or_else(|| negotiate_format(accept));
+
+    let key = response_key(source_key, format, spec.quality);
+    let etag = httpcache::etag(key);
+    let last_modified = server_start();
+
+    if httpcache::if_none_match_satisfied(&request_headers, &etag)
+        || httpcache::if_modified_since_satisfied(&request_headers, last_modified)
+    {
+        let mut headers = HeaderMap::new();
+        headers.insert("vary", HeaderValue::from_static("Accept"));
+        httpcache::apply_validators(&mut headers, config.cache_max_age, last_modified, &etag);
+        return Ok((StatusCode::NOT_MODIFIED, headers, Vec::new()));
+    }
+
+    let data = retrieve_image(source_key, &url, cache, fetcher, in_flight)
         .await
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+        .map_err(|e| match e {
+            fetcher::FetchError::Upstream(status) => status,
+            fetcher::FetchError::Other(_) => StatusCode::BAD_GATEWAY,
+        })?;
 
     // TODO: 处理图片
     let mut engine: Photon = data
@@ -69,36 +163,83 @@ async fn generate(
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     engine.apply(&spec.specs);
-    let image = engine.generate(ImageFormat::Png);
+
+    let image = engine.generate(format, spec.quality);
     info!("Finished processing: image size {}", image.len());
 
     let mut headers = HeaderMap::new();
-    headers.insert("content-type", HeaderValue::from_static("image/png"));
+    headers.insert(
+        "content-type",
+        HeaderValue::from_static(content_type(format)),
+    );
+    headers.insert("vary", HeaderValue::from_static("Accept"));
+    httpcache::apply_validators(&mut headers, config.cache_max_age, last_modified, &etag);
 
-    Ok((headers, image))
+    Ok((StatusCode::OK, headers, image))
 }
 
-async fn retrieve_image(url: &str, cache: Cache) -> Result<Bytes> {
-    let mut hasher = DefaultHasher::new();
-    url.hash(&mut hasher);
-    let key = hasher.finish();
+/// Recomputes the HMAC over the received `spec`/`url` and compares it
+/// against the signature supplied in the path, mirroring thumbor's path
+/// signing semantics. The literal `unsafe` token is only accepted while
+/// signing is disabled server-side.
+fn verify_signature(config: &Config, signature: &str, spec: &str, url: &str) -> Result<(), StatusCode> {
+    match &config.signing_key {
+        Some(key) => {
+            if signature == sign::UNSAFE_TOKEN {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            if sign::verify(config.signing_algorithm, key, spec, url, signature) {
+                Ok(())
+            } else {
+                Err(StatusCode::FORBIDDEN)
+            }
+        }
+        None => {
+            if signature == sign::UNSAFE_TOKEN {
+                Ok(())
+            } else {
+                Err(StatusCode::FORBIDDEN)
+            }
+        }
+    }
+}
 
-    let g = &mut cache.lock().await;
-    let data = match g.get(&key) {
-        Some(v) => {
+async fn retrieve_image(
+    key: u64,
+    url: &str,
+    cache: Cache,
+    fetcher: Arc<dyn Fetcher>,
+    in_flight: SingleFlight,
+) -> FetchResult {
+    {
+        let mut guard = cache.lock().await;
+        if let Some(data) = guard.get(&key) {
             info!("Match cache {}", key);
-            v.to_owned()
+            return Ok(data.clone());
         }
-        None => {
-            info!("Retrieve url");
-            let resp = reqwest::get(url).await?;
-            let data = resp.bytes().await?;
-            g.put(key, data.clone());
-            data
+    }
+
+    // Join an in-progress fetch for this key instead of each concurrent
+    // miss issuing its own redundant upstream request.
+    let guard = match in_flight.join(key).await {
+        Lead::Leader(guard) => guard,
+        Lead::Follower(mut rx) => {
+            return rx
+                .recv()
+                .await
+                .map_err(|_| fetcher::FetchError::Other(format!("upstream fetch for {url} was dropped")))?;
         }
     };
 
-    Ok(data)
+    info!("Retrieve url");
+    let result = fetcher.fetch(url).await;
+
+    if let Ok(data) = &result {
+        cache.lock().await.put(key, data.clone());
+    }
+    guard.finish(result.clone());
+
+    result
 }
 
 fn print_test_url(url: &str) {
@@ -109,5 +250,107 @@ fn print_test_url(url: &str) {
     let image_spec = ImageSpec::new(vec![spec1, spec2, spec3]);
     let s: String = image_spec.borrow().into();
     let test_image = percent_encode(url.as_bytes(), NON_ALPHANUMERIC).to_string();
-    println!("test url: http://localhost:3000/image/{}/{}", s, test_image);
+    println!(
+        "test url: http://localhost:3000/image/{}/{}/{}",
+        sign::UNSAFE_TOKEN,
+        s,
+        test_image
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sign::Algorithm;
+
+    fn config(signing_key: Option<&str>) -> Config {
+        Config {
+            signing_key: signing_key.map(str::to_string),
+            signing_algorithm: Algorithm::Sha1,
+            cache_max_age: 86400,
+            s3_region: None,
+            s3_bucket: None,
+            s3_endpoint: None,
+            file_root: None,
+            upstream_connect_timeout_secs: 5,
+            upstream_timeout_secs: 10,
+            upstream_user_agent: "test".to_string(),
+            upstream_proxy: None,
+        }
+    }
+
+    #[test]
+    fn unsafe_token_accepted_when_signing_disabled() {
+        let config = config(None);
+        assert!(verify_signature(&config, sign::UNSAFE_TOKEN, "100x100", "example.com/a.jpg").is_ok());
+    }
+
+    #[test]
+    fn non_unsafe_signature_rejected_when_signing_disabled() {
+        let config = config(None);
+        assert_eq!(
+            verify_signature(&config, "whatever", "100x100", "example.com/a.jpg"),
+            Err(StatusCode::FORBIDDEN)
+        );
+    }
+
+    #[test]
+    fn unsafe_token_rejected_when_signing_enabled() {
+        let config = config(Some("secret"));
+        assert_eq!(
+            verify_signature(&config, sign::UNSAFE_TOKEN, "100x100", "example.com/a.jpg"),
+            Err(StatusCode::FORBIDDEN)
+        );
+    }
+
+    #[test]
+    fn valid_signature_accepted_when_signing_enabled() {
+        let config = config(Some("secret"));
+        let signature = sign::sign(config.signing_algorithm, "secret", "100x100", "example.com/a.jpg");
+        assert!(verify_signature(&config, &signature, "100x100", "example.com/a.jpg").is_ok());
+    }
+
+    #[test]
+    fn invalid_signature_rejected_when_signing_enabled() {
+        let config = config(Some("secret"));
+        assert_eq!(
+            verify_signature(&config, "not-a-real-signature", "100x100", "example.com/a.jpg"),
+            Err(StatusCode::FORBIDDEN)
+        );
+    }
+
+    // `Path` percent-decodes matched segments before a handler ever sees
+    // them, so verification has to re-encode `url` to check the same bytes
+    // a signing client signed. A source URL with a query string (`?`/`&`,
+    // both percent-encoded on the wire) is what exposes the mismatch if
+    // that re-encoding step is missing.
+    #[tokio::test]
+    async fn signature_verifies_against_the_url_as_a_real_request_delivers_it() {
+        use axum::{body::Body, http::Request, routing::get, Router};
+        use tower::ServiceExt;
+
+        async fn check(Path((signature, spec, url)): Path<(String, String, String)>) -> StatusCode {
+            let config = config(Some("secret"));
+            let encoded_url = percent_encode(url.as_bytes(), NON_ALPHANUMERIC).to_string();
+            match verify_signature(&config, &signature, &spec, &encoded_url) {
+                Ok(()) => StatusCode::OK,
+                Err(status) => status,
+            }
+        }
+
+        let router = Router::new().route("/image/:signature/:spec/:url", get(check));
+
+        let config = config(Some("secret"));
+        let source_url = "https://example.com/a.jpg?w=100&h=200";
+        let encoded_source = percent_encode(source_url.as_bytes(), NON_ALPHANUMERIC).to_string();
+        let signature = sign::sign(config.signing_algorithm, "secret", "100x100", &encoded_source);
+
+        let uri = format!("/image/{signature}/100x100/{encoded_source}");
+        let response = router
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
 }
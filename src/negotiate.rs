@@ -0,0 +1,85 @@
+use image::ImageFormat;
+
+/// Picks the best image encoding to respond with, preferring modern
+/// formats the client advertises support for via `Accept` in the order
+/// AVIF > WebP > JPEG > PNG, falling back to PNG when nothing matches or no
+/// `Accept` header was sent. A token the client explicitly weighted `q=0`
+/// (e.g. `image/avif;q=0`) is treated as refused, not merely unpreferred.
+pub fn negotiate_format(accept: Option<&str>) -> ImageFormat {
+    let accept = match accept {
+        Some(accept) => accept.to_ascii_lowercase(),
+        None => return ImageFormat::Png,
+    };
+
+    let accepted: Vec<&str> = accept.split(',').filter(|token| !is_explicitly_rejected(token)).collect();
+
+    const PRIORITY: &[(&str, ImageFormat)] = &[
+        ("image/avif", ImageFormat::Avif),
+        ("image/webp", ImageFormat::WebP),
+        ("image/jpeg", ImageFormat::Jpeg),
+        ("image/png", ImageFormat::Png),
+    ];
+
+    PRIORITY
+        .iter()
+        .find(|(mime, _)| accepted.iter().any(|token| token.contains(mime)))
+        .map(|(_, format)| *format)
+        .unwrap_or(ImageFormat::Png)
+}
+
+/// Whether an `Accept` token carries an explicit `q=0` weight, meaning the
+/// client refuses that type rather than merely not preferring it.
+fn is_explicitly_rejected(token: &str) -> bool {
+    token.split(';').skip(1).any(|param| {
+        param
+            .trim()
+            .strip_prefix("q=")
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .is_some_and(|q| q == 0.0)
+    })
+}
+
+/// Returns the `content-type` value for a chosen output format.
+pub fn content_type(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Avif => "image/avif",
+        ImageFormat::WebP => "image/webp",
+        ImageFormat::Jpeg => "image/jpeg",
+        _ => "image/png",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefers_avif_over_everything() {
+        let format = negotiate_format(Some("image/png,image/webp,image/avif"));
+        assert_eq!(format, ImageFormat::Avif);
+    }
+
+    #[test]
+    fn prefers_webp_over_jpeg_and_png() {
+        let format = negotiate_format(Some("text/html,image/webp;q=0.9,image/jpeg"));
+        assert_eq!(format, ImageFormat::WebP);
+    }
+
+    #[test]
+    fn falls_back_to_png_when_nothing_matches() {
+        assert_eq!(negotiate_format(Some("text/html")), ImageFormat::Png);
+        assert_eq!(negotiate_format(None), ImageFormat::Png);
+    }
+
+    #[test]
+    fn q_zero_excludes_an_explicitly_refused_format() {
+        let format = negotiate_format(Some("image/avif;q=0, image/webp"));
+        assert_eq!(format, ImageFormat::WebP);
+    }
+
+    #[test]
+    fn q_zero_on_every_candidate_falls_back_to_png() {
+        let format = negotiate_format(Some("image/avif;q=0, image/webp;q=0"));
+        assert_eq!(format, ImageFormat::Png);
+    }
+}
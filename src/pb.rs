@@ -1,5 +1,7 @@
 use std::fmt;
 
+use image::ImageFormat;
+
 /// A single transform to apply to the source image, in request order.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Spec {
@@ -94,16 +96,39 @@ pub mod filter {
     }
 }
 
-/// The parsed `spec` path segment: the ordered transforms to apply to the
-/// source image.
+fn format_as_str(format: ImageFormat) -> &'static str {
+    match format {
+        ImageFormat::Avif => "avif",
+        ImageFormat::WebP => "webp",
+        ImageFormat::Jpeg => "jpeg",
+        ImageFormat::Png => "png",
+        _ => "png",
+    }
+}
+
+fn parse_format(value: &str) -> Option<ImageFormat> {
+    match value {
+        "avif" => Some(ImageFormat::Avif),
+        "webp" => Some(ImageFormat::WebP),
+        "jpeg" => Some(ImageFormat::Jpeg),
+        "png" => Some(ImageFormat::Png),
+        _ => None,
+    }
+}
+
+/// The parsed `spec` path segment: the ordered transforms to apply, plus an
+/// optional output `format`/`quality` override carried in the same segment.
+/// When unset, `format` falls back to `Accept`-based content negotiation.
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct ImageSpec {
     pub specs: Vec<Spec>,
+    pub format: Option<ImageFormat>,
+    pub quality: Option<u8>,
 }
 
 impl ImageSpec {
     pub fn new(specs: Vec<Spec>) -> Self {
-        Self { specs }
+        Self { specs, format: None, quality: None }
     }
 }
 
@@ -155,6 +180,16 @@ impl TryFrom<&str> for ImageSpec {
                     let filter = filter::Filter::parse(params).ok_or(ParseSpecError)?;
                     spec.specs.push(Spec::new_filter(filter));
                 }
+                "format" => {
+                    spec.format = Some(parse_format(params).ok_or(ParseSpecError)?);
+                }
+                "quality" => {
+                    let quality: u8 = params.parse().map_err(|_| ParseSpecError)?;
+                    if !(1..=100).contains(&quality) {
+                        return Err(ParseSpecError);
+                    }
+                    spec.quality = Some(quality);
+                }
                 _ => return Err(ParseSpecError),
             }
         }
@@ -175,6 +210,12 @@ impl From<&ImageSpec> for String {
                 Spec::Filter(filter) => tokens.push(format!("filter={}", filter.as_str())),
             }
         }
+        if let Some(format) = spec.format {
+            tokens.push(format!("format={}", format_as_str(format)));
+        }
+        if let Some(quality) = spec.quality {
+            tokens.push(format!("quality={quality}"));
+        }
         tokens.join(";")
     }
 }
@@ -191,6 +232,8 @@ mod tests {
                 Spec::new_watermark(20, 20),
                 Spec::new_filter(filter::Filter::Marine),
             ],
+            format: Some(ImageFormat::WebP),
+            quality: Some(80),
         };
 
         let encoded: String = (&spec).into();
@@ -209,4 +252,16 @@ mod tests {
     fn rejects_unknown_token() {
         assert!(ImageSpec::try_from("bogus=1").is_err());
     }
+
+    #[test]
+    fn accepts_boundary_quality_values() {
+        assert_eq!(ImageSpec::try_from("quality=1").unwrap().quality, Some(1));
+        assert_eq!(ImageSpec::try_from("quality=100").unwrap().quality, Some(100));
+    }
+
+    #[test]
+    fn rejects_out_of_range_quality() {
+        assert!(ImageSpec::try_from("quality=0").is_err());
+        assert!(ImageSpec::try_from("quality=101").is_err());
+    }
 }
@@ -0,0 +1,137 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::broadcast;
+
+use crate::fetcher::{FetchError, FetchResult};
+
+/// De-duplicates concurrent misses for the same cache key so that when N
+/// requests race to fetch the same source image, exactly one upstream fetch
+/// happens and the rest await and clone its result.
+#[derive(Clone, Default)]
+pub struct SingleFlight {
+    inner: Arc<Mutex<HashMap<u64, broadcast::Sender<FetchResult>>>>,
+}
+
+/// Outcome of joining a fetch: either this call is the leader and must
+/// perform the work itself (and call [`LeaderGuard::finish`] when done), or
+/// it's a follower waiting on the leader's result.
+pub enum Lead {
+    Leader(LeaderGuard),
+    Follower(broadcast::Receiver<FetchResult>),
+}
+
+/// Held by the leader for the duration of its fetch. [`LeaderGuard::finish`]
+/// broadcasts the result to followers and clears the in-flight entry. If the
+/// guard is instead dropped without finishing — the leader's future got
+/// cancelled, e.g. the client disconnected while `fetcher.fetch` was still
+/// in flight — the entry is cleared with an error anyway, so followers
+/// aren't left waiting on a channel nobody will ever send or close.
+pub struct LeaderGuard {
+    key: u64,
+    inner: Arc<Mutex<HashMap<u64, broadcast::Sender<FetchResult>>>>,
+    finished: bool,
+}
+
+impl LeaderGuard {
+    /// Broadcasts `result` to any followers and clears the in-flight entry
+    /// so a later miss starts a fresh fetch rather than replaying a stale
+    /// error.
+    pub fn finish(mut self, result: FetchResult) {
+        self.finished = true;
+        if let Some(tx) = self.inner.lock().unwrap().remove(&self.key) {
+            let _ = tx.send(result);
+        }
+    }
+}
+
+impl Drop for LeaderGuard {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Some(tx) = self.inner.lock().unwrap().remove(&self.key) {
+            let _ = tx.send(Err(FetchError::Other(
+                "upstream fetch was cancelled before it completed".to_string(),
+            )));
+        }
+    }
+}
+
+impl SingleFlight {
+    /// Registers interest in `key`, returning whether the caller should lead
+    /// the fetch or follow an in-progress one.
+    pub async fn join(&self, key: u64) -> Lead {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(tx) = inner.get(&key) {
+            Lead::Follower(tx.subscribe())
+        } else {
+            let (tx, _rx) = broadcast::channel(1);
+            inner.insert(key, tx);
+            Lead::Leader(LeaderGuard {
+                key,
+                inner: self.inner.clone(),
+                finished: false,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn follower_receives_the_leaders_result() {
+        let sf = SingleFlight::default();
+        let guard = match sf.join(1).await {
+            Lead::Leader(guard) => guard,
+            Lead::Follower(_) => panic!("expected to lead"),
+        };
+        let mut rx = match sf.join(1).await {
+            Lead::Follower(rx) => rx,
+            Lead::Leader(_) => panic!("expected to follow"),
+        };
+
+        guard.finish(Ok(Bytes::from_static(b"data")));
+
+        assert_eq!(rx.recv().await.unwrap().unwrap(), Bytes::from_static(b"data"));
+    }
+
+    #[tokio::test]
+    async fn finishing_clears_the_entry_so_the_next_join_leads() {
+        let sf = SingleFlight::default();
+        let guard = match sf.join(2).await {
+            Lead::Leader(guard) => guard,
+            Lead::Follower(_) => panic!("expected to lead"),
+        };
+
+        guard.finish(Err(FetchError::Other("boom".to_string())));
+
+        match sf.join(2).await {
+            Lead::Leader(_) => {}
+            Lead::Follower(_) => panic!("a failed fetch must not poison the key for later requests"),
+        }
+    }
+
+    #[tokio::test]
+    async fn dropping_the_leader_without_finishing_unblocks_followers() {
+        let sf = SingleFlight::default();
+        let guard = match sf.join(3).await {
+            Lead::Leader(guard) => guard,
+            Lead::Follower(_) => panic!("expected to lead"),
+        };
+        let mut rx = match sf.join(3).await {
+            Lead::Follower(rx) => rx,
+            Lead::Leader(_) => panic!("expected to follow"),
+        };
+
+        drop(guard);
+
+        let result = rx.recv().await.expect("follower must be unblocked, not hang forever");
+        assert!(result.is_err());
+    }
+}